@@ -1,24 +1,42 @@
-use log::info;
+use log::{error, info, warn};
 use plotters::{
     chart::ChartBuilder,
     drawing::IntoDrawingArea,
-    element::PathElement,
+    element::{PathElement, Rectangle},
     prelude::{BitMapBackend, IntoFont},
     series::LineSeries,
     style::{colors, Color},
 };
-use regex::Regex;
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, Body, Client, Method, Request, StatusCode};
+use rand::{distributions::Alphanumeric, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
 use std::{
     env::args,
-    process::{Command, Output},
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-
-/// The name of the command used to benchmark the HTTP server
-const BENCH_CMD: &'static str = "httperf";
+use tokio::{sync::Semaphore, time::interval};
 
 /// The number of times the tester should re-run a route for consistency
 const SAMPLES: usize = 10;
 
+/// The number of concurrent connections each load-generation burst opens
+const CONNECTIONS: usize = 4;
+
+/// The largest number of unused tokens the rate limiter will let accumulate, so
+/// an idle period can't be followed by an unbounded burst.
+const MAX_BURST: usize = 64;
+
+/// How many times a single sample is retried after a transient failure before
+/// it is abandoned.
+const RETRIES: usize = 3;
+
 /// The default tests to run
 const DEFAULT_TESTS: [TestKind; 3] = [
     TestKind::Latency,
@@ -37,153 +55,984 @@ const OUT_FILES: [&'static str; 3] = [
 /// - a test that tests the average time for a response to be received
 /// - a test that determines the maximum number of bytes serviceable
 /// - a test that determines the maximum number of requests serviceable
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 enum TestKind {
     Latency,
     ThroughputBytes,
     ThroughputReq,
 }
 
+/// Summary statistics reduced from the `SAMPLES` rates collected for a single
+/// query size:
+/// - the arithmetic mean of the samples
+/// - the median (middle value, or the average of the two middle values)
+/// - the population standard deviation about the mean
+/// - the smallest and largest sample observed
+#[derive(Serialize, Deserialize)]
+struct TestResult {
+    mean: f32,
+    median: f32,
+    stddev: f32,
+    min: f32,
+    max: f32,
+}
+
+impl TestResult {
+    /// Reduces a batch of per-run rates into its summary statistics. The slice
+    /// must be non-empty; an empty batch has no meaningful centre to report.
+    fn from_samples(samples: &[f32]) -> TestResult {
+        let n = samples.len() as f32;
+
+        let mean = samples.iter().sum::<f32>() / n;
+
+        // The median needs the samples in ascending order; clone so the caller
+        // keeps the original measurement order.
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mid = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 0 {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+
+        // Population standard deviation: the root of the mean squared deviation
+        // from the mean.
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n;
+        let stddev = variance.sqrt();
+
+        TestResult {
+            mean,
+            median,
+            stddev,
+            min: sorted[0],
+            max: *sorted.last().unwrap(),
+        }
+    }
+}
+
+impl TestKind {
+    /// The y-axis label used when plotting this kind of test.
+    fn y_title(&self) -> &'static str {
+        match self {
+            TestKind::Latency => "Avg. Response Latency (ms)",
+            TestKind::ThroughputReq => "Max. Throughput (req./sec.)",
+            TestKind::ThroughputBytes => "Max. Throughput (KB/sec.)",
+        }
+    }
+
+    /// Reduces the requests recorded by one load-generation burst into the
+    /// single rate this kind of test cares about:
+    /// - `Latency`: the mean per-request latency in milliseconds
+    /// - `ThroughputReq`: completed requests per second over the burst
+    /// - `ThroughputBytes`: body kilobytes per second over the burst
+    fn rate(&self, stats: &[RequestStats], elapsed: Duration) -> f32 {
+        let secs = elapsed.as_secs_f32();
+
+        match self {
+            TestKind::Latency => {
+                let total_ms: f32 = stats.iter().map(|s| s.latency.as_secs_f32() * 1000.0).sum();
+                total_ms / stats.len() as f32
+            }
+            TestKind::ThroughputReq => stats.len() as f32 / secs,
+            TestKind::ThroughputBytes => {
+                let total_bytes: u64 = stats.iter().map(|s| s.bytes).sum();
+                (total_bytes as f32 / 1024.0) / secs
+            }
+        }
+    }
+}
+
+/// The outcome of a single request issued by the load generator:
+/// - the wall-clock latency from send to fully-received body
+/// - the number of body bytes received
+/// - the HTTP status the server replied with
+/// - whether the failure was fatal (the connection could not be established)
+/// rather than a transient per-request error
+struct RequestStats {
+    latency: Duration,
+    bytes: u64,
+    status: StatusCode,
+    fatal_error: bool,
+}
+
+/// Why a sample failed to produce a usable rate:
+/// - `Transient`: a one-off hiccup (empty burst, reset, no successful replies)
+///   that is worth retrying
+/// - `Fatal`: the server is unreachable, so the whole sweep should stop
+enum BenchError {
+    Transient(String),
+    Fatal(String),
+}
+
+/// Abstracts the wire protocol a burst speaks so HTTP/1.1, HTTP/2 or a future
+/// protocol can be swapped in without touching the generator. Each adapter owns
+/// whatever client it needs and issues one request per `send_request` call.
+#[async_trait]
+trait ProtocolAdapter: Send + Sync {
+    /// Issues a single GET request and reports how it went.
+    async fn send_request(&self) -> RequestStats;
+}
+
+/// Sends one GET per call over a shared hyper client. The `http2` flag selects
+/// between HTTP/1.1 (the default) and prior-knowledge HTTP/2 cleartext.
+struct HttpAdapter {
+    client: Client<HttpConnector, Body>,
+    uri: hyper::Uri,
+}
+
+impl HttpAdapter {
+    /// Builds an adapter targeting `uri`, negotiating HTTP/2 up front when
+    /// `http2` is set and otherwise speaking HTTP/1.1.
+    fn new(uri: hyper::Uri, http2: bool) -> HttpAdapter {
+        let client = Client::builder().http2_only(http2).build_http();
+
+        HttpAdapter { client, uri }
+    }
+}
+
+#[async_trait]
+impl ProtocolAdapter for HttpAdapter {
+    async fn send_request(&self) -> RequestStats {
+        let req = Request::builder()
+            .method(Method::GET)
+            .uri(&self.uri)
+            .body(Body::empty())
+            .expect("Couldn't build request.");
+
+        let start = Instant::now();
+
+        match self.client.request(req).await {
+            Ok(resp) => {
+                let status = resp.status();
+                let body = hyper::body::to_bytes(resp.into_body())
+                    .await
+                    .map(|b| b.len() as u64)
+                    .unwrap_or(0);
+
+                RequestStats {
+                    latency: start.elapsed(),
+                    bytes: body,
+                    status,
+                    fatal_error: false,
+                }
+            }
+            // Only a failure to establish the connection is fatal: the server
+            // is almost certainly unreachable. Other request errors (e.g. a
+            // reset) are transient and simply don't count as a success.
+            Err(e) => RequestStats {
+                latency: start.elapsed(),
+                bytes: 0,
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                fatal_error: e.is_connect(),
+            },
+        }
+    }
+}
+
+/// How a worker paces successive requests:
+/// - `Closed`: the next request only begins once the previous one completes
+///   (throughput is bounded by the server's own response time)
+/// - `Open`: requests are issued at a fixed arrival rate regardless of how long
+///   responses take, which surfaces queueing and coordinated-omission effects
+#[derive(Clone, Copy)]
+enum LoopMode {
+    Closed,
+    Open,
+}
+
+/// A token-bucket rate limiter. A background task refills one token every
+/// `1 / rps` seconds up to `MAX_BURST`, and `acquire` blocks a worker until a
+/// token is available, spending it on return.
+struct RateLimiter {
+    tokens: Arc<Semaphore>,
+}
+
+impl RateLimiter {
+    /// Builds a limiter targeting `rps` requests per second and spawns its
+    /// refill task.
+    fn new(rps: f32) -> RateLimiter {
+        let tokens = Arc::new(Semaphore::new(0));
+        let refill = tokens.clone();
+
+        // One token per request; the period is the reciprocal of the rate.
+        let period = Duration::from_secs_f32(1.0 / rps);
+
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+
+            loop {
+                ticker.tick().await;
+
+                // Cap accumulation so a quiet stretch can't license a burst.
+                if refill.available_permits() < MAX_BURST {
+                    refill.add_permits(1);
+                }
+            }
+        });
+
+        RateLimiter { tokens }
+    }
+
+    /// Blocks until a token is available, then spends it.
+    async fn acquire(&self) {
+        // The semaphore is never closed, so acquiring cannot fail; `forget`
+        // keeps the permit spent rather than returning it on drop.
+        self.tokens
+            .acquire()
+            .await
+            .expect("Rate limiter closed.")
+            .forget();
+    }
+}
+
+/// An in-process load generator: it opens `connections` concurrent workers that
+/// hammer the target adapter until either `duration` elapses or `num_requests`
+/// requests have been issued across all workers, recording every request. An
+/// optional `rate_limiter` paces arrivals and `mode` selects open- or
+/// closed-loop behaviour.
+struct LoadGenerator {
+    connections: usize,
+    duration: Option<Duration>,
+    num_requests: Option<usize>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    mode: LoopMode,
+}
+
+impl LoadGenerator {
+    /// Drives `adapter` to completion and returns every request's stats
+    /// alongside the wall-clock time the burst took.
+    async fn run(&self, adapter: Arc<dyn ProtocolAdapter>) -> (Vec<RequestStats>, Duration) {
+        // Shared request budget in request-count mode; ignored under a deadline.
+        let remaining = Arc::new(AtomicUsize::new(self.num_requests.unwrap_or(0)));
+        let deadline = self.duration.map(|d| Instant::now() + d);
+
+        let start = Instant::now();
+
+        // Every request's stats flow back over this channel; in open-loop mode
+        // the in-flight request tasks outlive the workers that launched them.
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut handles = Vec::with_capacity(self.connections);
+
+        for _ in 0..self.connections {
+            let adapter = adapter.clone();
+            let remaining = remaining.clone();
+            let limiter = self.rate_limiter.clone();
+            let mode = self.mode;
+            let tx = tx.clone();
+
+            handles.push(tokio::spawn(async move {
+                loop {
+                    match deadline {
+                        // Duration mode: keep firing until the clock runs out.
+                        Some(dl) if Instant::now() >= dl => break,
+                        Some(_) => {}
+                        // Request-count mode: claim one request from the budget.
+                        None => {
+                            let claimed = remaining
+                                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| {
+                                    v.checked_sub(1)
+                                })
+                                .is_ok();
+
+                            if !claimed {
+                                break;
+                            }
+                        }
+                    }
+
+                    // Pace the arrival if a rate limiter is configured.
+                    if let Some(limiter) = &limiter {
+                        limiter.acquire().await;
+                    }
+
+                    match mode {
+                        LoopMode::Closed => {
+                            let s = adapter.send_request().await;
+                            let fatal = s.fatal_error;
+                            let _ = tx.send(s);
+
+                            // A fatal error means the server is gone; stop here.
+                            if fatal {
+                                break;
+                            }
+                        }
+                        // Open-loop: fire and move straight to the next arrival
+                        // without waiting for the response.
+                        LoopMode::Open => {
+                            let adapter = adapter.clone();
+                            let tx = tx.clone();
+
+                            tokio::spawn(async move {
+                                let s = adapter.send_request().await;
+                                let _ = tx.send(s);
+                            });
+                        }
+                    }
+                }
+            }));
+        }
+
+        // Drop the original sender so `rx` closes once every worker and every
+        // spawned request task has dropped its clone.
+        drop(tx);
+
+        for h in handles {
+            h.await.expect("Load worker panicked.");
+        }
+
+        let mut all = Vec::new();
+        while let Some(s) = rx.recv().await {
+            all.push(s);
+        }
+
+        (all, start.elapsed())
+    }
+}
+
+/// A serializable record of one completed test, written beside the PNG so runs
+/// can be diffed over time or re-plotted later without re-running the sweep:
+/// - the server address and port the sweep was run against
+/// - the reduced `TestResult` for each query size, keyed by query length
+/// - the kind of test that produced the results
+/// - the query words that made up the sweep
+/// - a UNIX timestamp (seconds) recording when the sweep finished
+/// - the RNG seed, when the dictionary was synthetically generated, documenting
+///   exactly what was run
+#[derive(Serialize, Deserialize)]
+struct BenchmarkSummary {
+    server_addr: String,
+    server_port: u16,
+    results: Vec<(f32, TestResult)>,
+    kind: TestKind,
+    dict: Vec<String>,
+    timestamp: u64,
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+/// A named server endpoint to benchmark: its legend label plus the address and
+/// port requests are sent to.
+#[derive(Clone)]
+struct Target {
+    name: String,
+    addr: String,
+    port: u16,
+}
+
 /// A test has multiple data dependencies:
-/// - The address of the server it is testing on
-/// - The port of the server it should test against
+/// - The named endpoints it is testing, run through the identical sweep
 /// - The kind of the test
 /// - The dictionary to use for the test
 /// - The name of the file the test's results should be written to
+/// - The optional target request rate (requests/sec.) to pace arrivals at
+/// - Whether arrivals are open-loop (fixed rate) or closed-loop (default)
+/// - Whether to speak HTTP/2 instead of the default HTTP/1.1
+/// - An optional per-sample burst duration; without it each burst fires a fixed
+///   number of requests instead
+/// - The RNG seed, when the dictionary was synthetically generated
 struct Test<'a> {
-    server_addr: &'a str,
-    server_port: &'a u16,
+    targets: Vec<Target>,
     dict: Vec<String>,
     kind: TestKind,
     out_file: &'a str,
+    rate: Option<f32>,
+    open_loop: bool,
+    http2: bool,
+    duration: Option<Duration>,
+    seed: Option<u64>,
 }
 
-/// A script that starts successive httperf instances with varying query sizes,
+/// A script that drives the native load generator at varying query sizes,
 /// where the address of the server is the first cli arg, the port of the
 /// server the second, and the dictionary of query words are the last arguments.
-fn main() {
+#[tokio::main]
+async fn main() {
     // For prefixing logs with severity labels
     env_logger::init();
 
     let mut args = args();
+    let prog = args.next().expect("No program name");
+
+    // Collect the remaining arguments so flags can be pulled out before the
+    // positional server/port/dictionary arguments are read.
+    let mut rest = args.collect::<Vec<String>>();
 
     // Handle no arguments, which means usage
-    if args.len() == 1 {
+    if rest.is_empty() {
         println!(
-            "./{} <server_addr> <port_number> <query_word1> <query_word2> ...",
-            args.next().expect("No program name")
+            "./{} [--rate <req/sec>] [--open-loop] [--http2] [--duration <secs>] \
+             [--generate <N> --seed <S>] [--target <name=addr:port> ...] \
+             <server_addr> <port_number> <query_word1> ...",
+            prog
         );
+        println!("./{} --load <summary.json>", prog);
 
         return;
     }
 
-    let server_addr = args.nth(1).expect("Missing server address.");
+    // Open-loop mode drives a fixed arrival rate regardless of response time.
+    let open_loop = take_flag(&mut rest, "--open-loop");
+
+    // Speak HTTP/2 (prior knowledge) instead of the default HTTP/1.1.
+    let http2 = take_flag(&mut rest, "--http2");
+
+    // An optional per-burst duration (seconds); without it each burst fires a
+    // fixed number of requests. A non-positive value would never let a burst
+    // run, so reject it cleanly.
+    let duration = match take_option(&mut rest, "--duration") {
+        Some(v) => {
+            let secs = v.parse::<f32>().expect("Invalid duration.");
+
+            if !(secs.is_finite() && secs > 0.0) {
+                error!("--duration must be a finite positive number, got {secs}");
 
-    // TCP port numbers are 2^16 max
-    let port = args
-        .next()
-        .expect("Missing port number.")
-        .parse::<u16>()
-        .expect("Invalid port number.");
+                return;
+            }
 
-    let dictionary = args.collect::<Vec<String>>();
+            Some(Duration::from_secs_f32(secs))
+        }
+        None => None,
+    };
+
+    // An optional target request rate; without it the generator runs flat out.
+    // A zero, negative or non-finite rate would make the limiter's refill period
+    // blow up, so reject it cleanly rather than panicking later.
+    let rate = match take_option(&mut rest, "--rate") {
+        Some(v) => {
+            let rps = v.parse::<f32>().expect("Invalid request rate.");
+
+            if !(rps.is_finite() && rps > 0.0) {
+                error!("--rate must be a finite positive number, got {rps}");
+
+                return;
+            }
+
+            Some(rps)
+        }
+        None => None,
+    };
+
+    // Open-loop is a fixed arrival rate, so it is meaningless without a target
+    // rate; it would also leave the worker loop without a yield point, spawning
+    // request tasks unboundedly. Require `--rate` alongside it.
+    if open_loop && rate.is_none() {
+        error!("--open-loop requires --rate to set the arrival rate");
+
+        return;
+    }
+
+    // Any number of `--target name=addr:port` flags select endpoints to compare
+    // side by side; each is pulled out before the positional arguments.
+    let mut targets: Vec<Target> = Vec::new();
+    while let Some(spec) = take_option(&mut rest, "--target") {
+        targets.push(parse_target(&spec));
+    }
+
+    // `--generate N` synthesizes N query terms from a seeded RNG (defaulting to
+    // seed 0) instead of taking them as positional arguments, so sweeps are
+    // reproducible across machines.
+    let generate = take_option(&mut rest, "--generate")
+        .map(|v| v.parse::<usize>().expect("Invalid term count."));
+    let seed = take_option(&mut rest, "--seed")
+        .map(|v| v.parse::<u64>().expect("Invalid seed."));
+
+    // Re-plot a previously saved summary instead of running any benchmarks. The
+    // output PNG is the summary path with its extension swapped to `png`.
+    if rest.first().map(String::as_str) == Some("--load") {
+        let path = rest.get(1).expect("Missing summary path.");
+        let json = std::fs::read_to_string(path).expect("Couldn't read summary.");
+        let summary: BenchmarkSummary =
+            serde_json::from_str(&json).expect("Couldn't parse summary.");
+
+        let out_file = replace_extension(path, "png");
+        render_summary(&summary, &out_file);
+
+        return;
+    }
+
+    // With the flags extracted there may be nothing left to benchmark — e.g. a
+    // `--generate`/`--seed` line that forgot its `--target` and has no
+    // positional server. Print usage rather than panicking in the fallback.
+    if targets.is_empty() && rest.is_empty() {
+        println!(
+            "./{} [--rate <req/sec>] [--open-loop] [--http2] [--duration <secs>] \
+             [--generate <N> --seed <S>] [--target <name=addr:port> ...] \
+             <server_addr> <port_number> <query_word1> ...",
+            prog
+        );
+
+        return;
+    }
+
+    let mut args = rest.into_iter();
+
+    // When no `--target` flags were given, fall back to the positional
+    // server/port as a single unnamed endpoint for backwards compatibility.
+    if targets.is_empty() {
+        let addr = args.next().expect("Missing server address.");
+
+        // TCP port numbers are 2^16 max
+        let port = args
+            .next()
+            .expect("Missing port number.")
+            .parse::<u16>()
+            .expect("Invalid port number.");
+
+        targets.push(Target {
+            name: format!("{addr}:{port}"),
+            addr,
+            port,
+        });
+    }
+
+    // A synthetic dictionary is written to a tempfile that must outlive the
+    // sweep; the seed recorded in each summary documents what was run.
+    let (dictionary, seed, _dict_file) = match generate {
+        Some(n) => {
+            let seed = seed.unwrap_or(0);
+            let (dict, file) = generate_dictionary(n, seed);
+
+            (dict, Some(seed), Some(file))
+        }
+        None => (args.collect::<Vec<String>>(), None, None),
+    };
 
     for i in 0..3 {
         do_test(Test {
-            server_addr: server_addr.as_str(),
-            server_port: &port,
+            targets: targets.clone(),
             dict: dictionary.clone(),
             kind: DEFAULT_TESTS[i],
             out_file: OUT_FILES[i],
+            rate,
+            open_loop,
+            http2,
+            duration,
+            seed,
         })
+        .await
+    }
+}
+
+/// Deterministically generates `n` random alphanumeric query terms of varying
+/// length from `seed`, writing them one per line to a tempfile-backed
+/// dictionary. The same seed always produces the same terms. Returns the terms
+/// alongside the temp file, which the caller must keep alive for its lifetime.
+fn generate_dictionary(n: usize, seed: u64) -> (Vec<String>, tempfile::NamedTempFile) {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let mut file = tempfile::NamedTempFile::new().expect("Couldn't open dictionary tempfile.");
+    let mut terms = Vec::with_capacity(n);
+
+    for _ in 0..n {
+        // Terms are 3-12 characters so the sweep exercises a spread of lengths.
+        let len = rng.gen_range(3..=12);
+        let term: String = (&mut rng)
+            .sample_iter(Alphanumeric)
+            .take(len)
+            .map(char::from)
+            .collect();
+
+        writeln!(file, "{term}").expect("Couldn't write dictionary term.");
+        terms.push(term);
+    }
+
+    (terms, file)
+}
+
+/// Parses a `name=addr:port` target specification into a [`Target`].
+fn parse_target(spec: &str) -> Target {
+    let (name, endpoint) = spec.split_once('=').expect("Target missing name=.");
+    let (addr, port) = endpoint.rsplit_once(':').expect("Target missing :port.");
+
+    Target {
+        name: name.to_string(),
+        addr: addr.to_string(),
+        port: port.parse::<u16>().expect("Invalid target port."),
+    }
+}
+
+/// Removes `flag` from `args` if present, returning whether it was found.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    if let Some(pos) = args.iter().position(|a| a == flag) {
+        args.remove(pos);
+
+        true
+    } else {
+        false
     }
 }
 
-/// Performs the indicated test, crashing the program if an error occurs.
-fn do_test<'a>(test: Test<'a>) {
+/// Removes `flag` and its following value from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let pos = args.iter().position(|a| a == flag)?;
+    args.remove(pos);
+
+    assert!(pos < args.len(), "Missing value for {flag}.");
+
+    Some(args.remove(pos))
+}
+
+/// Runs one load-generation burst against `uri` and reduces it to the rate this
+/// kind of test cares about. A burst where every request failed to connect is
+/// reported as [`BenchError::Fatal`]; a burst with no successful replies is
+/// [`BenchError::Transient`] and worth retrying.
+async fn run_sample(
+    uri: &hyper::Uri,
+    kind: TestKind,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    mode: LoopMode,
+    http2: bool,
+    duration: Option<Duration>,
+) -> Result<f32, BenchError> {
+    let adapter: Arc<dyn ProtocolAdapter> = Arc::new(HttpAdapter::new(uri.clone(), http2));
+
+    let generator = LoadGenerator {
+        connections: CONNECTIONS,
+        // Either run for a fixed duration or, when none is set, a fixed number
+        // of requests.
+        num_requests: duration.is_none().then_some(SAMPLES),
+        duration,
+        rate_limiter,
+        mode,
+    };
+
+    let (stats, elapsed) = generator.run(adapter).await;
+
+    // Every request failing to even connect means the server is unreachable.
+    if !stats.is_empty() && stats.iter().all(|s| s.fatal_error) {
+        return Err(BenchError::Fatal(format!(
+            "all {} requests to {} failed to connect",
+            stats.len(),
+            uri
+        )));
+    }
+
+    // Only requests that came back with a success status contribute a rate.
+    let ok = stats
+        .into_iter()
+        .filter(|s| s.status.is_success())
+        .collect::<Vec<RequestStats>>();
+
+    if ok.is_empty() {
+        return Err(BenchError::Transient(format!(
+            "no successful replies from {uri}"
+        )));
+    }
+
+    Ok(kind.rate(&ok, elapsed))
+}
+
+/// Performs the indicated test, flushing a partial plot if a fatal error stops
+/// the sweep early.
+async fn do_test<'a>(test: Test<'a>) {
     // Convenient aliases for the execution of the test
     let Test {
-        server_addr,
-        server_port: port,
+        targets,
         dict: dictionary,
         kind,
         out_file,
+        rate,
+        open_loop,
+        http2,
+        duration,
+        seed,
     } = test;
 
-    let throughput_tester = Box::new(move |mut cmd: Command| {
-                    cmd.arg("--num-conns");
-                    cmd.arg(SAMPLES.to_string());
-
-                    return cmd;
-                });
-
-    let (y_title, regex_expr, mut query_args): (&str, &str, Box<dyn FnMut(Command) -> Command>) =
-        match kind {
-            TestKind::Latency => (
-                "Avg. Response Latency (ms)",
-                r"Connection time.*avg (\S+) max",
-                Box::new(move |mut cmd: Command| {
-                    cmd.arg("--num-calls");
-                    cmd.arg(SAMPLES.to_string());
-
-                    return cmd;
-                }),
-            ),
-            TestKind::ThroughputReq => (
-                "Max. Throughput (req./sec.)",
-                r"Request rate: (\S+) req",
-                throughput_tester,
-            ),
-            TestKind::ThroughputBytes => (
-                "Max. Throughput (KB/sec.)",
-                r"Net I/O: (\S+) ",
-                throughput_tester,
-            ),
+    // A shared limiter paces arrivals across all bursts when a rate is set.
+    let rate_limiter = rate.map(|rps| Arc::new(RateLimiter::new(rps)));
+    let mode = if open_loop {
+        LoopMode::Open
+    } else {
+        LoopMode::Closed
+    };
+
+    let multiple = targets.len() > 1;
+
+    // Set once a fatal error is seen, halting the remaining queries and targets
+    // so whatever was collected can still be flushed to a partial plot.
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // The reduced results for each endpoint, paired with its legend label, so
+    // they can be plotted as grouped bars.
+    let mut comparison: Vec<(String, Vec<(f32, TestResult)>)> = Vec::new();
+
+    for target in &targets {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+
+        // Where the reduced statistics from each subsequent query are put, keyed
+        // by the complexity (length) of the query that produced them
+        let mut buf: Vec<(f32, TestResult)> = Vec::new();
+
+        for i in 0..dictionary.len() {
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+
+            // Stores a + separated list of words from the n words from the
+            // provided dictionary currently being tested
+            let query: String = (&dictionary[0..=i])
+                .iter()
+                .cloned()
+                .reduce(|a, b| format!("{a}+{b}"))
+                .expect("Couldn't build query.");
+            let query_url = format!(
+                "http://{}:{}/query?terms={}",
+                target.addr, target.port, query
+            );
+
+            info!("Running query #{} against {}: {}", i, target.name, query_url);
+
+            let uri = match query_url.parse::<hyper::Uri>() {
+                Ok(uri) => uri,
+                // A malformed URI won't get better on retry and means no server
+                // can be reached, so treat it as fatal.
+                Err(e) => {
+                    error!("invalid query URI {query_url}: {e}; stopping sweep");
+                    stop.store(true, Ordering::SeqCst);
+                    break;
+                }
+            };
+
+            // Re-run the same query `SAMPLES` times so variance between bursts
+            // is captured rather than averaged away within a single burst.
+            let mut rates: Vec<f32> = Vec::with_capacity(SAMPLES);
+
+            for _ in 0..SAMPLES {
+                // Retry a transient failure a few times before abandoning the
+                // sample; a fatal failure trips the stop flag instead.
+                for attempt in 0..=RETRIES {
+                    match run_sample(&uri, kind, rate_limiter.clone(), mode, http2, duration).await
+                    {
+                        Ok(rate) => {
+                            rates.push(rate);
+                            break;
+                        }
+                        Err(BenchError::Transient(msg)) => {
+                            warn!(
+                                "transient error on {} query #{} (attempt {}/{}): {}",
+                                target.name,
+                                i,
+                                attempt + 1,
+                                RETRIES + 1,
+                                msg
+                            );
+
+                            if attempt == RETRIES {
+                                warn!(
+                                    "abandoning sample on {} query #{} after {} retries",
+                                    target.name, i, RETRIES
+                                );
+                            }
+                        }
+                        Err(BenchError::Fatal(msg)) => {
+                            error!("fatal error on {}: {}; stopping sweep", target.name, msg);
+                            stop.store(true, Ordering::SeqCst);
+                            break;
+                        }
+                    }
+                }
+
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+            }
+
+            // With every sample abandoned there's nothing to summarize for this
+            // query; skip it rather than reducing an empty batch.
+            if rates.is_empty() {
+                continue;
+            }
+
+            let result = TestResult::from_samples(&rates);
+
+            info!(
+                "Query #{} on {} finished: median - {}, mean - {}, stddev - {}",
+                i, target.name, result.median, result.mean, result.stddev
+            );
+
+            buf.push((query.len() as f32, result));
+        }
+
+        // Nothing landed for this endpoint (e.g. it was down from the start);
+        // don't emit an empty summary or plot group for it.
+        if buf.is_empty() {
+            continue;
+        }
+
+        // Seconds since the UNIX epoch; the system clock should be past 1970, so
+        // a failure here is genuinely exceptional.
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("System clock before UNIX epoch.")
+            .as_secs();
+
+        let summary = BenchmarkSummary {
+            server_addr: target.addr.clone(),
+            server_port: target.port,
+            results: buf,
+            kind,
+            dict: dictionary.clone(),
+            timestamp,
+            seed,
         };
 
-    // Where average response times from each subsequent test is put
-    let mut buf: Vec<(f32, f32)> = Vec::new();
+        // Persist the raw numbers beside the PNG so the run can be re-plotted or
+        // diffed later; disambiguate by endpoint name when comparing several.
+        let json_file = if multiple {
+            replace_extension(out_file, &format!("{}.json", sanitize(&target.name)))
+        } else {
+            replace_extension(out_file, "json")
+        };
+        let json = serde_json::to_string_pretty(&summary).expect("Couldn't serialize summary.");
+        std::fs::write(&json_file, json).expect("Couldn't write summary.");
 
-    for i in 0..dictionary.len() {
-        // Stores a + separated list of words from the n words from the provided
-        // dictionary currently being tested
-        let query: String = (&dictionary[0..=i])
-            .iter()
-            .cloned()
-            .reduce(|a, b| format!("{a}+{b}"))
-            .expect("Couldn't build query.");
-        let query_url = format!("/query?terms={}", query);
-
-        info!(
-            "Running query #{}: http://{}:{}{}",
-            i, server_addr, port, query_url
-        );
+        comparison.push((target.name.clone(), summary.results));
+    }
+
+    render_comparison(&comparison, kind, out_file);
+}
+
+/// Replaces any character that isn't alphanumeric with an underscore so an
+/// endpoint name is safe to splice into a filename.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// The colours cycled through when drawing one bar group per endpoint.
+const PALETTE: [plotters::style::RGBColor; 5] = [
+    colors::RED,
+    colors::BLUE,
+    colors::GREEN,
+    colors::MAGENTA,
+    colors::CYAN,
+];
+
+/// Renders a grouped bar chart comparing every endpoint's mean rate at each
+/// query-word-length bucket to `out_file`. Each endpoint gets a coloured bar in
+/// every bucket it has a result for, a matching legend entry, and a vertical
+/// error bar spanning one standard deviation about the mean.
+///
+/// The bars are drawn as `Rectangle`s rather than a plotters `Histogram`
+/// series: `Histogram` bins a single series into one bar per segment and has no
+/// notion of side-by-side groups, so rendering one offset bar per endpoint at
+/// each bucket — the grouped comparison this mode is for — needs manual
+/// placement. Bar height and whiskers both use the mean so an error bar always
+/// sits on its bar top.
+fn render_comparison(comparison: &[(String, Vec<(f32, TestResult)>)], kind: TestKind, out_file: &str) {
+    let y_title = kind.y_title();
+
+    // Endpoints may skip a query size whose samples were all abandoned, so align
+    // buckets by the actual query length each result carries rather than by
+    // position. The sorted union of lengths defines the categorical axis.
+    let mut lengths = comparison
+        .iter()
+        .flat_map(|(_, r)| r.iter().map(|(x, _)| *x))
+        .collect::<Vec<f32>>();
+    lengths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    lengths.dedup();
+
+    let buckets = lengths.len();
+    if buckets == 0 {
+        return;
+    }
+
+    // The tallest bar plus its error bar sets the y extent; bars start at zero.
+    let max_y = comparison
+        .iter()
+        .flat_map(|(_, r)| r.iter().map(|(_, s)| s.mean + s.stddev))
+        .fold(0.0f32, f32::max);
+
+    let canvas = BitMapBackend::new(out_file, (640, 480)).into_drawing_area();
+    canvas.fill(&colors::WHITE).expect("Couldn't fill plot.");
+
+    let canvas = canvas.margin(10, 10, 10, 10);
+    let mut plt = ChartBuilder::on(&canvas)
+        .caption(
+            format!("Index Query Word Length vs {y_title}"),
+            ("sans-serif", 16).into_font(),
+        )
+        .x_label_area_size(20)
+        .y_label_area_size(40)
+        // A half-bucket of padding either side keeps edge groups off the axes.
+        .build_cartesian_2d(-0.5f32..(buckets as f32 - 0.5), 0.0f32..(max_y * 1.1))
+        .expect("Couldn't make plot canvas.");
+
+    // Label each categorical bucket with the query length that produced it.
+    plt.configure_mesh()
+        .x_labels(buckets)
+        .x_label_formatter(&|x| {
+            lengths
+                .get(x.round() as usize)
+                .map(|l| format!("{l}"))
+                .unwrap_or_default()
+        })
+        .draw()
+        .expect("Couldn't draw plot.");
 
-        let mut test_cmd = Command::new(BENCH_CMD);
-        test_cmd
-            .arg("--server")
-            .arg(&server_addr)
-            .arg("--port")
-            .arg(port.to_string())
-            .arg("--uri")
-            .arg(query_url);
+    // Bars for all endpoints share a bucket's width, sitting side by side.
+    let group_width = 0.8f32;
+    let bar_width = group_width / comparison.len() as f32;
 
-        // Apply arguments specific to the test type
-        test_cmd = query_args(test_cmd);
+    for (e, (name, results)) in comparison.iter().enumerate() {
+        let color = PALETTE[e % PALETTE.len()];
 
-        let test_out = test_cmd.output().expect("Failed to execute test.");
+        // Offset this endpoint's bar within each bucket so groups don't overlap.
+        let left_edge = move |bucket: usize| bucket as f32 - group_width / 2.0 + e as f32 * bar_width;
 
-        // The average number of seconds the server took to process the query
-        let rate = parse_output(test_out, regex_expr);
-        buf.push((query.len() as f32, rate));
+        // Place each result in the shared bucket matching its query length; the
+        // lengths are integer-valued so the equality is exact.
+        let placed = results
+            .iter()
+            .filter_map(|(x, s)| lengths.iter().position(|l| l == x).map(|b| (b, s)))
+            .collect::<Vec<(usize, &TestResult)>>();
 
-        info!("Query #{} finished: avg. response time - {}ms", i, rate);
+        plt.draw_series(placed.iter().map(|(b, s)| {
+            let left = left_edge(*b);
+            Rectangle::new([(left, 0.0), (left + bar_width, s.mean)], color.filled())
+        }))
+        .expect("Couldn't draw series.")
+        .label(name.clone())
+        .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+
+        // Error bars are centred on each of this endpoint's bars.
+        for (b, s) in &placed {
+            let center = left_edge(*b) + bar_width / 2.0;
+            plt.draw_series(std::iter::once(PathElement::new(
+                vec![(center, s.mean - s.stddev), (center, s.mean + s.stddev)],
+                &colors::BLACK,
+            )))
+            .expect("Couldn't draw error bar.");
+        }
     }
 
-    let mut max_x = buf.iter().map(|(x, _)| x).cloned().collect::<Vec<f32>>();
+    plt.configure_series_labels()
+        .background_style(&colors::WHITE.mix(0.8))
+        .border_style(&colors::BLACK)
+        .draw()
+        .expect("Couldn't draw plot.");
+}
+
+/// Renders the plot for a completed (or loaded) summary to `out_file`, drawing
+/// the median trend line with per-point error bars spanning one standard
+/// deviation about the mean.
+fn render_summary(summary: &BenchmarkSummary, out_file: &str) {
+    let buf = &summary.results;
+    let y_title = summary.kind.y_title();
+
+    let mut max_x = buf.iter().map(|(x, _)| *x).collect::<Vec<f32>>();
     max_x.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-    let mut max_y = buf.iter().map(|(_, y)| y).cloned().collect::<Vec<f32>>();
+    // Error bars extend a standard deviation either side of the mean, so the
+    // plotted y-range has to cover that whole spread, not just the medians.
+    let mut max_y = buf
+        .iter()
+        .flat_map(|(_, r)| [r.mean - r.stddev, r.mean + r.stddev, r.min, r.max])
+        .collect::<Vec<f32>>();
     max_y.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
     // Used for building axis extents
@@ -208,10 +1057,24 @@ fn do_test<'a>(test: Test<'a>) {
         .expect("Couldn't make plot canvas.");
     plt.configure_mesh().draw().expect("Couldn't draw plot.");
 
-    plt.draw_series(LineSeries::new(buf, &colors::RED))
-        .expect("Couldn't draw series.")
-        .label(format!("{y_title} n={SAMPLES}"))
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &colors::RED));
+    // The median line is the headline trend across query complexities.
+    plt.draw_series(LineSeries::new(
+        buf.iter().map(|(x, r)| (*x, r.median)),
+        &colors::RED,
+    ))
+    .expect("Couldn't draw series.")
+    .label(format!("{y_title} n={SAMPLES}"))
+    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &colors::RED));
+
+    // Overlay a vertical error bar per point spanning +/- one standard
+    // deviation about the mean, so variance is visible alongside the median.
+    for (x, r) in buf {
+        plt.draw_series(std::iter::once(PathElement::new(
+            vec![(*x, r.mean - r.stddev), (*x, r.mean + r.stddev)],
+            &colors::BLACK,
+        )))
+        .expect("Couldn't draw error bar.");
+    }
 
     plt.configure_series_labels()
         .background_style(&colors::WHITE.mix(0.8))
@@ -220,22 +1083,44 @@ fn do_test<'a>(test: Test<'a>) {
         .expect("Couldn't draw plot.");
 }
 
-/// Returns the number of requests per second from the httper test, followed
-/// by the complexity of the queries issued.
-fn parse_output<'a>(output: Output, regex: &'a str) -> f32 {
-    // Use a regex to capture the
-    // `Reply rate [replies/s]: min 0.0 avg 0.0 max 0.0 stddev 0.0 (0 samples)`
-    // line of the output
-    let raw_out = String::from_utf8(output.stdout).expect("Test had no output.");
-    let str_rate = Regex::new(regex)
-        .expect("Could not build regex.")
-        .captures(raw_out.as_ref())
-        .and_then(|capture| capture.get(1))
-        .map(|r_match| r_match.as_str())
-        .expect("No reply rate in test output.");
-
-    // The reply rate is in decimal format
-    str_rate
-        .parse::<f32>()
-        .expect("Reply rate from test was not a valid integer.")
+/// Returns `path` with its final extension replaced by `ext` (e.g.
+/// `foo.png` -> `foo.json`), appending the extension if there was none.
+fn replace_extension(path: &str, ext: &str) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, _)) => format!("{stem}.{ext}"),
+        None => format!("{path}.{ext}"),
+    }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_length_samples() {
+        let r = TestResult::from_samples(&[1.0, 2.0, 3.0]);
+
+        assert_eq!(r.mean, 2.0);
+        // Middle value of the sorted odd-length batch.
+        assert_eq!(r.median, 2.0);
+        assert_eq!(r.min, 1.0);
+        assert_eq!(r.max, 3.0);
+        // Population stddev: sqrt(((-1)^2 + 0 + 1^2) / 3).
+        assert!((r.stddev - (2.0f32 / 3.0).sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn even_length_samples() {
+        // Deliberately unsorted so the median sort is exercised.
+        let r = TestResult::from_samples(&[4.0, 1.0, 3.0, 2.0]);
+
+        assert_eq!(r.mean, 2.5);
+        // Average of the two middle values for an even-length batch.
+        assert_eq!(r.median, 2.5);
+        assert_eq!(r.min, 1.0);
+        assert_eq!(r.max, 4.0);
+        // Population stddev: sqrt((2.25 + 0.25 + 0.25 + 2.25) / 4).
+        assert!((r.stddev - 1.25f32.sqrt()).abs() < 1e-6);
+    }
+}
+